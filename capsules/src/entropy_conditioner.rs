@@ -0,0 +1,379 @@
+//! Conditions a low-entropy byte source into a full-entropy
+//! [Entropy32](kernel::hil::entropy::Entropy32).
+//!
+//! The `entropy` HIL documentation requires that output carry ~1 bit
+//! of entropy per bit. Many raw noise sources, exposed as
+//! [Entropy8](kernel::hil::entropy::Entropy8), do not meet that bar on
+//! their own. `Conditioner` absorbs enough raw bytes from such a
+//! source to collect at least 256 bits of raw entropy, runs them
+//! through a cryptographic hash, and yields the digest as full-entropy
+//! 32-bit words to its own `Client32`.
+
+use core::cell::Cell;
+use kernel::hil::digest::Digest;
+use kernel::hil::entropy::{Client32, Client8, Continue, Entropy32, Entropy8, Mode};
+use kernel::ReturnCode;
+
+/// Number of 32-bit words produced by one SHA-256 digest.
+const OUTPUT_WORDS: usize = 8;
+
+/// Wraps an [Entropy8](kernel::hil::entropy::Entropy8) source,
+/// hashing its raw bytes into full-entropy 32-bit words.
+pub struct Conditioner<'a, E: Entropy8<'a>, H: Digest<[u8; 32]>> {
+    source: &'a E,
+    hash: &'a H,
+    client: Cell<Option<&'a Client32>>,
+    /// Raw bytes that must be absorbed per conditioned output block.
+    bytes_per_block: usize,
+    /// Raw bytes still needed to fill the current input block.
+    remaining: Cell<usize>,
+    /// Conditioned output words ready to be handed to the client.
+    output: Cell<[u32; OUTPUT_WORDS]>,
+    /// How many words of `output` are valid and unconsumed.
+    available: Cell<usize>,
+}
+
+impl<'a, E: Entropy8<'a>, H: Digest<[u8; 32]>> Conditioner<'a, E, H> {
+    /// `bytes_per_block` is how many raw bytes must be absorbed per
+    /// conditioned output block, derived by the board from its
+    /// input-entropy-per-byte estimate so that at least 256 bits of
+    /// raw entropy are collected per block (e.g. 40 raw bytes for a
+    /// source estimated at 6.4 bits of min-entropy per byte).
+    pub fn new(source: &'a E, hash: &'a H, bytes_per_block: usize) -> Conditioner<'a, E, H> {
+        Conditioner {
+            source: source,
+            hash: hash,
+            client: Cell::new(None),
+            bytes_per_block: bytes_per_block,
+            remaining: Cell::new(bytes_per_block),
+            output: Cell::new([0; OUTPUT_WORDS]),
+            available: Cell::new(0),
+        }
+    }
+
+    fn block_complete(&self) {
+        let mut digest = [0u8; 32];
+        self.hash.run(&mut digest);
+
+        let mut words = [0u32; OUTPUT_WORDS];
+        for (i, word) in words.iter_mut().enumerate() {
+            let base = i * 4;
+            *word = u32::from_le_bytes([
+                digest[base],
+                digest[base + 1],
+                digest[base + 2],
+                digest[base + 3],
+            ]);
+        }
+        self.output.set(words);
+        self.available.set(OUTPUT_WORDS);
+        self.remaining.set(self.bytes_per_block);
+    }
+
+    /// Hands the buffered, undelivered tail of the current output
+    /// block to the client. A client that returns `Continue::Done`
+    /// consumes the whole tail, drawn or not. When it returns
+    /// `Continue::More`, only the words it actually draws from the
+    /// iterator are counted consumed: a client that returns
+    /// `Continue::More` after only partially draining it is resumed
+    /// from where it left off on the next `get()`, rather than the
+    /// whole block being considered consumed (which would silently
+    /// drop the undrained words) or never being redelivered at all
+    /// (which would hang the client forever).
+    fn deliver(&self) {
+        let client = match self.client.get() {
+            Some(client) => client,
+            None => return,
+        };
+
+        let available = self.available.get();
+        let words = self.output.get();
+        let produced = &words[OUTPUT_WORDS - available..];
+
+        let taken = Cell::new(0);
+        let mut iter = produced
+            .iter()
+            .cloned()
+            .inspect(|_| taken.set(taken.get() + 1));
+
+        match client.entropy_available(&mut iter, ReturnCode::SUCCESS) {
+            Continue::Done => self.available.set(0),
+            Continue::More => self.available.set(available - taken.get()),
+        }
+    }
+}
+
+impl<'a, E: Entropy8<'a>, H: Digest<[u8; 32]>> Entropy32<'a> for Conditioner<'a, E, H> {
+    fn get(&self) -> ReturnCode {
+        if self.available.get() > 0 {
+            // A block is already buffered from a previous call; hand
+            // it to the client instead of asking the raw source for
+            // more bytes it doesn't need yet.
+            self.source.cancel();
+            self.deliver();
+            return ReturnCode::SUCCESS;
+        }
+        // Ask the raw source for exactly the bytes needed to complete
+        // the current input block, rather than polling it repeatedly
+        // as partial entropy trickles in.
+        self.source.get_with(self.remaining.get(), Mode::GoodOnly)
+    }
+
+    fn cancel(&self) -> ReturnCode {
+        self.source.cancel()
+    }
+
+    fn set_client(&'a self, client: &'a Client32) {
+        self.client.set(Some(client));
+        self.source.set_client(self);
+    }
+}
+
+impl<'a, E: Entropy8<'a>, H: Digest<[u8; 32]>> Client8 for Conditioner<'a, E, H> {
+    fn entropy_available(&self, entropy: &mut Iterator<Item = u8>, error: ReturnCode) -> Continue {
+        let client = match self.client.get() {
+            Some(client) => client,
+            None => return Continue::Done,
+        };
+
+        if error != ReturnCode::SUCCESS {
+            return client.entropy_available(&mut core::iter::empty(), error);
+        }
+
+        // Whether a block was delivered this call determines whether
+        // we are done for now (Continue::Done) or still waiting on
+        // more raw bytes to finish the first one (Continue::More).
+        let mut delivered_a_block = false;
+
+        loop {
+            match entropy.next() {
+                Some(byte) => {
+                    self.hash.add_data(&[byte]);
+                    let remaining = self.remaining.get() - 1;
+                    self.remaining.set(remaining);
+                    if remaining == 0 {
+                        self.block_complete();
+                        self.deliver();
+                        delivered_a_block = true;
+                        // A source may hand over a larger burst than
+                        // the current block needed; any bytes still
+                        // left in `entropy` are carried into the
+                        // start of the next block instead of being
+                        // dropped.
+                    }
+                }
+                None => {
+                    // The source went dry: ask for more and resume
+                    // hashing from where we left off the next time we
+                    // are called, unless a block was already
+                    // delivered this call.
+                    return if delivered_a_block {
+                        Continue::Done
+                    } else {
+                        Continue::More
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::vec::Vec;
+    use test_util::Recorder;
+
+    struct MockSource {
+        get_with_calls: Cell<usize>,
+        last_min_bytes: Cell<usize>,
+        cancel_calls: Cell<usize>,
+    }
+
+    impl MockSource {
+        fn new() -> MockSource {
+            MockSource {
+                get_with_calls: Cell::new(0),
+                last_min_bytes: Cell::new(0),
+                cancel_calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl<'a> Entropy8<'a> for MockSource {
+        fn get(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn get_with(&self, min_bytes: usize, _mode: Mode) -> ReturnCode {
+            self.get_with_calls.set(self.get_with_calls.get() + 1);
+            self.last_min_bytes.set(min_bytes);
+            ReturnCode::SUCCESS
+        }
+        fn cancel(&self) -> ReturnCode {
+            self.cancel_calls.set(self.cancel_calls.get() + 1);
+            ReturnCode::SUCCESS
+        }
+        fn set_client(&'a self, _client: &'a Client8) {}
+    }
+
+    struct MockDigest {
+        absorbed: RefCell<Vec<u8>>,
+    }
+
+    impl MockDigest {
+        fn new() -> MockDigest {
+            MockDigest {
+                absorbed: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Digest<[u8; 32]> for MockDigest {
+        fn add_data(&self, data: &[u8]) {
+            self.absorbed.borrow_mut().extend_from_slice(data);
+        }
+        fn run(&self, digest: &mut [u8; 32]) {
+            // A digest that depends on what was absorbed, so tests can
+            // tell a real block apart from an empty one.
+            *digest = [0u8; 32];
+            digest[0] = self.absorbed.borrow().len() as u8;
+            self.absorbed.borrow_mut().clear();
+        }
+    }
+
+    /// A `Client32` that only draws the first `take` words from the
+    /// iterator it is handed and then stops (as a client with a
+    /// bounded buffer of its own would), always reporting
+    /// `Continue::More` so tests can exercise partial-drain
+    /// bookkeeping.
+    struct PartialClient {
+        take: usize,
+        calls: RefCell<Vec<Vec<u32>>>,
+    }
+
+    impl PartialClient {
+        fn new(take: usize) -> PartialClient {
+            PartialClient {
+                take: take,
+                calls: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Client32 for PartialClient {
+        fn entropy_available(&self, entropy: &mut Iterator<Item = u32>, _error: ReturnCode) -> Continue {
+            let mut drained = Vec::new();
+            for _ in 0..self.take {
+                match entropy.next() {
+                    Some(word) => drained.push(word),
+                    None => break,
+                }
+            }
+            self.calls.borrow_mut().push(drained);
+            Continue::More
+        }
+    }
+
+    #[test]
+    fn source_running_dry_mid_block_resumes_hashing_on_the_next_callback() {
+        let source = MockSource::new();
+        let hash = MockDigest::new();
+        let conditioner = Conditioner::new(&source, &hash, 4);
+
+        let recorder = Recorder::new();
+        conditioner.client.set(Some(&recorder));
+
+        // Only 2 of the 4 bytes needed arrive before the source goes
+        // dry: the block must not complete yet.
+        let mut first = [1u8, 2u8].iter().cloned();
+        let result = conditioner.entropy_available(&mut first, ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::More);
+        assert!(recorder.calls.borrow().is_empty());
+        assert_eq!(conditioner.remaining.get(), 2);
+
+        // The remaining 2 bytes complete the block and deliver it.
+        let mut second = [3u8, 4u8].iter().cloned();
+        let result = conditioner.entropy_available(&mut second, ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0.len(), OUTPUT_WORDS);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+    }
+
+    #[test]
+    fn a_burst_larger_than_one_block_carries_the_leftover_into_the_next_block() {
+        let source = MockSource::new();
+        let hash = MockDigest::new();
+        let conditioner = Conditioner::new(&source, &hash, 4);
+
+        let recorder = Recorder::new();
+        conditioner.client.set(Some(&recorder));
+
+        // The source hands over 6 bytes in one callback even though
+        // only 4 are needed to complete the current block: the
+        // leftover 2 bytes must be absorbed into the start of the
+        // next block rather than discarded.
+        let mut bytes = [1u8, 2u8, 3u8, 4u8, 5u8, 6u8].iter().cloned();
+        let result = conditioner.entropy_available(&mut bytes, ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0.len(), OUTPUT_WORDS);
+
+        // Only 2 more bytes should be needed to complete the next
+        // block, proving the 2 leftover bytes were absorbed rather
+        // than dropped (which would have left 4 remaining).
+        assert_eq!(conditioner.remaining.get(), 2);
+    }
+
+    #[test]
+    fn a_partially_drained_block_is_redelivered_on_the_next_get() {
+        let source = MockSource::new();
+        let hash = MockDigest::new();
+        let conditioner = Conditioner::new(&source, &hash, 4);
+
+        let partial = PartialClient::new(3);
+        conditioner.client.set(Some(&partial));
+
+        let mut bytes = [1u8, 2u8, 3u8, 4u8].iter().cloned();
+        let result = conditioner.entropy_available(&mut bytes, ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+        assert_eq!(partial.calls.borrow()[0].len(), 3);
+        assert_eq!(conditioner.available.get(), OUTPUT_WORDS - 3);
+
+        // The client only drained 3 of the 8 words; the remaining 5
+        // must be handed back out, not dropped or re-hashed, and the
+        // raw source must not be polled for bytes it doesn't need yet.
+        let result = conditioner.get();
+        assert_eq!(result, ReturnCode::SUCCESS);
+        assert_eq!(source.get_with_calls.get(), 0);
+        assert_eq!(partial.calls.borrow()[1].len(), 3);
+        assert_eq!(conditioner.available.get(), OUTPUT_WORDS - 3 - 3);
+    }
+
+    #[test]
+    fn get_called_again_while_a_block_is_already_buffered() {
+        let source = MockSource::new();
+        let hash = MockDigest::new();
+        let conditioner = Conditioner::new(&source, &hash, 4);
+
+        let recorder = Recorder::new();
+        conditioner.client.set(Some(&recorder));
+
+        let mut bytes = [1u8, 2u8, 3u8, 4u8].iter().cloned();
+        conditioner.entropy_available(&mut bytes, ReturnCode::SUCCESS);
+        assert_eq!(recorder.calls.borrow().len(), 1);
+
+        // Nothing is left buffered (the recorder always drains fully),
+        // so asking again must poll the source for a fresh block
+        // rather than redelivering an empty one.
+        let result = conditioner.get();
+        assert_eq!(result, ReturnCode::SUCCESS);
+        assert_eq!(source.get_with_calls.get(), 1);
+        assert_eq!(source.last_min_bytes.get(), 4);
+    }
+}
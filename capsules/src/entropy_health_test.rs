@@ -0,0 +1,425 @@
+//! NIST SP 800-90B continuous health tests for entropy sources.
+//!
+//! `HealthTested` wraps any [Entropy32](kernel::hil::entropy::Entropy32)
+//! source and interposes the two continuous tests required by SP
+//! 800-90B Section 4.4 on every raw sample drawn from it: the
+//! Repetition Count Test (4.4.1) and the Adaptive Proportion Test
+//! (4.4.2). Both tests run over the *raw* sample stream, so boards
+//! should place this capsule directly on top of the noise source and
+//! condition its output afterwards (see `entropy_conditioner`), not
+//! the other way around.
+//!
+//! If either test detects a degraded or stuck source, `HealthTested`
+//! stops forwarding samples and signals `FAIL` through the client's
+//! `entropy_available` callback, the same way the underlying source
+//! would signal a hardware error.
+//!
+//! Until 1024 consecutive samples have passed both tests (the startup
+//! requirement of SP 800-90B Section 4.3), `get()` collects samples
+//! internally and does not yield anything to the client.
+
+use core::cell::Cell;
+use kernel::hil::entropy::{Client32, Continue, Entropy32};
+use kernel::ReturnCode;
+
+/// Number of consecutive samples that must pass both continuous
+/// tests before startup health testing is considered complete.
+const STARTUP_SAMPLES: usize = 1024;
+
+/// Repetition Count Test (SP 800-90B Section 4.4.1).
+struct RepetitionCount {
+    /// Cutoff `C = 1 + ceil(20 / H)` at which a run of identical
+    /// samples is declared a failure.
+    cutoff: usize,
+    /// Most recently observed sample, `A` in the spec.
+    last: Cell<Option<u32>>,
+    /// Length of the current run of `last`, `B` in the spec.
+    run: Cell<usize>,
+}
+
+impl RepetitionCount {
+    /// `centibits` is the configured min-entropy per sample, `H`,
+    /// expressed in hundredths of a bit so the cutoff can be computed
+    /// without floating point.
+    fn new(centibits: usize) -> RepetitionCount {
+        RepetitionCount {
+            cutoff: 1 + ceil_div(2000, centibits),
+            last: Cell::new(None),
+            run: Cell::new(0),
+        }
+    }
+
+    fn reset(&self) {
+        self.last.set(None);
+        self.run.set(0);
+    }
+
+    /// Returns `false` if this sample causes the test to fail.
+    fn consume(&self, sample: u32) -> bool {
+        if self.last.get() == Some(sample) {
+            let run = self.run.get() + 1;
+            self.run.set(run);
+            run < self.cutoff
+        } else {
+            self.last.set(Some(sample));
+            self.run.set(1);
+            true
+        }
+    }
+}
+
+/// Adaptive Proportion Test (SP 800-90B Section 4.4.2).
+struct AdaptiveProportion {
+    /// Window size `W` (512 for byte sources, 1024 for bitwise ones).
+    window: usize,
+    /// Cutoff derived from the binomial tail at the configured
+    /// min-entropy. Computed offline (e.g. with the NIST reference
+    /// tool) and supplied by the board, since the kernel has no
+    /// floating point support for the tail computation.
+    cutoff: usize,
+    /// Reference sample `A` for the current window, if one has been
+    /// chosen yet.
+    reference: Cell<Option<u32>>,
+    /// Number of samples seen so far in the current window,
+    /// including the reference sample.
+    seen: Cell<usize>,
+    /// Number of samples in the current window equal to `reference`.
+    matches: Cell<usize>,
+}
+
+impl AdaptiveProportion {
+    fn new(window: usize, cutoff: usize) -> AdaptiveProportion {
+        AdaptiveProportion {
+            window: window,
+            cutoff: cutoff,
+            reference: Cell::new(None),
+            seen: Cell::new(0),
+            matches: Cell::new(0),
+        }
+    }
+
+    fn reset(&self) {
+        self.reference.set(None);
+        self.seen.set(0);
+        self.matches.set(0);
+    }
+
+    /// Returns `false` if this sample causes the test to fail.
+    fn consume(&self, sample: u32) -> bool {
+        if self.reference.get().is_none() {
+            self.reference.set(Some(sample));
+            self.seen.set(1);
+            self.matches.set(0);
+            return true;
+        }
+
+        let matches = if self.reference.get() == Some(sample) {
+            self.matches.get() + 1
+        } else {
+            self.matches.get()
+        };
+        self.matches.set(matches);
+        self.seen.set(self.seen.get() + 1);
+
+        let ok = matches < self.cutoff;
+
+        if self.seen.get() >= self.window {
+            self.reference.set(None);
+            self.seen.set(0);
+            self.matches.set(0);
+        }
+
+        ok
+    }
+}
+
+fn ceil_div(numerator: usize, denominator: usize) -> usize {
+    (numerator + denominator - 1) / denominator
+}
+
+/// Wraps an [Entropy32](kernel::hil::entropy::Entropy32) source with
+/// SP 800-90B startup and continuous health tests.
+pub struct HealthTested<'a, E: Entropy32<'a>> {
+    source: &'a E,
+    client: Cell<Option<&'a Client32>>,
+    repetition: RepetitionCount,
+    proportion: AdaptiveProportion,
+    /// Number of consecutive samples that have passed both tests
+    /// since the last failure or since startup began.
+    healthy_run: Cell<usize>,
+    /// Whether the startup test requirement has been satisfied.
+    started: Cell<bool>,
+}
+
+impl<'a, E: Entropy32<'a>> HealthTested<'a, E> {
+    /// `centibits` is the configured min-entropy per sample in
+    /// hundredths of a bit, used to derive the Repetition Count Test
+    /// cutoff. `window` and `proportion_cutoff` parameterize the
+    /// Adaptive Proportion Test; `window` should be 512 for byte
+    /// sources and 1024 for bitwise sources, and `proportion_cutoff`
+    /// is the binomial tail cutoff precomputed for that window and
+    /// `centibits`.
+    pub fn new(
+        source: &'a E,
+        centibits: usize,
+        window: usize,
+        proportion_cutoff: usize,
+    ) -> HealthTested<'a, E> {
+        HealthTested {
+            source: source,
+            client: Cell::new(None),
+            repetition: RepetitionCount::new(centibits),
+            proportion: AdaptiveProportion::new(window, proportion_cutoff),
+            healthy_run: Cell::new(0),
+            started: Cell::new(false),
+        }
+    }
+
+    /// Feeds one raw sample through both continuous tests, updating
+    /// startup progress. Returns `false` if either test fails.
+    fn test_sample(&self, sample: u32) -> bool {
+        let ok = self.repetition.consume(sample) & self.proportion.consume(sample);
+        if !ok {
+            self.repetition.reset();
+            self.proportion.reset();
+            self.healthy_run.set(0);
+            return false;
+        }
+
+        if !self.started.get() {
+            let run = self.healthy_run.get() + 1;
+            self.healthy_run.set(run);
+            if run >= STARTUP_SAMPLES {
+                self.started.set(true);
+            }
+        }
+
+        true
+    }
+}
+
+impl<'a, E: Entropy32<'a>> Entropy32<'a> for HealthTested<'a, E> {
+    fn get(&self) -> ReturnCode {
+        self.source.get()
+    }
+
+    fn cancel(&self) -> ReturnCode {
+        self.source.cancel()
+    }
+
+    fn set_client(&'a self, client: &'a Client32) {
+        self.client.set(Some(client));
+        self.source.set_client(self);
+    }
+}
+
+/// Number of tested samples buffered before being handed to the
+/// client in one batch. Chosen to match the block size used by
+/// `entropy_conditioner`'s output blocks.
+const BATCH: usize = 8;
+
+impl<'a, E: Entropy32<'a>> Client32 for HealthTested<'a, E> {
+    fn entropy_available(
+        &self,
+        entropy: &mut Iterator<Item = u32>,
+        error: ReturnCode,
+    ) -> Continue {
+        let client = match self.client.get() {
+            Some(client) => client,
+            None => return Continue::Done,
+        };
+
+        if error != ReturnCode::SUCCESS {
+            return client.entropy_available(entropy, error);
+        }
+
+        // Buffer tested samples rather than streaming them straight
+        // to the client, so that a test failure partway through a
+        // batch can be reported on its own, as a single callback,
+        // instead of first handing out the good prefix and then
+        // separately reporting the failure. `entropy` may legally
+        // hand over more than one batch's worth in a single callback,
+        // so every sample must be run through the continuous tests
+        // before this function returns -- stopping at the first
+        // `BATCH` boundary would leave the tail of a larger burst
+        // completely untested.
+        let mut buffer = [0u32; BATCH];
+        let mut filled = 0;
+        // Once the client reports `Continue::Done`, further samples
+        // are still run through the continuous tests below but are
+        // not forwarded to it; a test failure is still reported to it
+        // with `FAIL`.
+        let mut client_done = false;
+
+        while let Some(sample) = entropy.next() {
+            if !self.test_sample(sample) {
+                client.entropy_available(&mut core::iter::empty(), ReturnCode::FAIL);
+                return Continue::Done;
+            }
+
+            if self.started.get() && !client_done {
+                buffer[filled] = sample;
+                filled += 1;
+                if filled == BATCH {
+                    let result =
+                        client.entropy_available(&mut buffer[..BATCH].iter().cloned(), ReturnCode::SUCCESS);
+                    filled = 0;
+                    client_done = result == Continue::Done;
+                }
+            }
+            // Otherwise the sample was consumed by the continuous
+            // tests above but is not surfaced to the client: either
+            // it went toward completing the startup requirement, or
+            // the client already has as much as it asked for.
+        }
+
+        if filled > 0 && !client_done {
+            let result =
+                client.entropy_available(&mut buffer[..filled].iter().cloned(), ReturnCode::SUCCESS);
+            client_done = result == Continue::Done;
+        }
+
+        if client_done {
+            Continue::Done
+        } else {
+            // The client never reported itself satisfied: either
+            // nothing was available, every sample read went toward
+            // the startup requirement, or the client wants more than
+            // this callback delivered. Ask to be called again once
+            // more raw samples are available.
+            Continue::More
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::Recorder;
+
+    #[test]
+    fn repetition_count_flags_a_long_run() {
+        // 100 centibits/sample (1 bit) => cutoff = 1 + ceil(2000 / 100) = 21.
+        let rc = RepetitionCount::new(100);
+        for _ in 0..20 {
+            assert!(rc.consume(7));
+        }
+        assert!(!rc.consume(7));
+    }
+
+    #[test]
+    fn repetition_count_resets_on_a_new_value() {
+        let rc = RepetitionCount::new(100);
+        for _ in 0..20 {
+            assert!(rc.consume(1));
+        }
+        assert!(rc.consume(2));
+        for _ in 0..19 {
+            assert!(rc.consume(2));
+        }
+    }
+
+    #[test]
+    fn adaptive_proportion_flags_excess_matches_within_a_window() {
+        let ap = AdaptiveProportion::new(8, 4);
+        assert!(ap.consume(9)); // reference sample
+        assert!(ap.consume(9)); // 1 match
+        assert!(ap.consume(9)); // 2 matches
+        assert!(ap.consume(9)); // 3 matches
+        assert!(!ap.consume(9)); // 4th match reaches the cutoff
+    }
+
+    #[test]
+    fn adaptive_proportion_resets_each_window() {
+        let ap = AdaptiveProportion::new(4, 1);
+        assert!(ap.consume(1)); // reference
+        assert!(ap.consume(2));
+        assert!(ap.consume(3));
+        assert!(ap.consume(4)); // window complete, resets
+        assert!(ap.consume(5)); // new reference, no carried-over count
+    }
+
+    struct NoopSource;
+
+    impl<'a> Entropy32<'a> for NoopSource {
+        fn get(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn cancel(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn set_client(&'a self, _client: &'a Client32) {}
+    }
+
+    #[test]
+    fn a_failed_test_reports_exactly_one_failure_callback() {
+        let source = NoopSource;
+        // 300 centibits/sample => repetition cutoff = 1 + ceil(2000 /
+        // 300) = 8. The proportion window/cutoff are set wide enough
+        // (1024/1023) that it cannot possibly be the test that fires
+        // within the 8 samples fed below, so this exercises the
+        // Repetition Count Test specifically.
+        let health = HealthTested::new(&source, 300, 1024, 1023);
+        health.started.set(true); // skip the 1024-sample startup requirement
+
+        let recorder = Recorder::new();
+        health.client.set(Some(&recorder));
+
+        // 8 identical samples reach the repetition cutoff on the last
+        // one, before a full BATCH could ever be buffered and handed
+        // to the client.
+        let mut stream = core::iter::repeat(5u32).take(8);
+        let result = health.entropy_available(&mut stream, ReturnCode::SUCCESS);
+
+        assert_eq!(result, Continue::Done);
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].0.is_empty());
+        assert!(calls[0].1 == ReturnCode::FAIL);
+    }
+
+    #[test]
+    fn a_repetition_failure_past_the_first_batch_is_still_detected() {
+        let source = NoopSource;
+        // 250 centibits/sample => repetition cutoff = 1 + ceil(2000 /
+        // 250) = 9, one past BATCH (8). A buggy implementation that
+        // stops draining `entropy` at the first BATCH boundary would
+        // never pull the 9th sample at all and so would never catch
+        // this failure.
+        let health = HealthTested::new(&source, 250, 1024, 1023);
+        health.started.set(true);
+
+        let recorder = Recorder::new();
+        health.client.set(Some(&recorder));
+
+        let mut stream = core::iter::repeat(5u32).take(9);
+        let result = health.entropy_available(&mut stream, ReturnCode::SUCCESS);
+
+        assert_eq!(result, Continue::Done);
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, vec![5, 5, 5, 5, 5, 5, 5, 5]);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+        assert!(calls[1].0.is_empty());
+        assert!(calls[1].1 == ReturnCode::FAIL);
+    }
+
+    #[test]
+    fn healthy_samples_are_forwarded_once_started() {
+        let source = NoopSource;
+        let health = HealthTested::new(&source, 100, 8, 4);
+        health.started.set(true);
+
+        let recorder = Recorder::new();
+        health.client.set(Some(&recorder));
+
+        let mut stream = (0u32..4).into_iter();
+        let result = health.entropy_available(&mut stream, ReturnCode::SUCCESS);
+
+        assert_eq!(result, Continue::Done);
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, vec![0, 1, 2, 3]);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+    }
+}
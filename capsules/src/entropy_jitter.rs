@@ -0,0 +1,305 @@
+//! Interrupt-timing jitter entropy source.
+//!
+//! Many microcontrollers Tock targets have no hardware TRNG, and so
+//! cannot implement [Entropy32](kernel::hil::entropy::Entropy32) at
+//! all. `JitterEntropy` harvests the jitter in interrupt arrival
+//! times instead: each time a board-supplied `service_interrupt()` is
+//! called from an interrupt handler, it reads a free-running cycle
+//! counter, takes the delta from the previous reading, whitens it,
+//! and folds it into a mixing buffer. Once enough independent events
+//! have contributed, the mixed buffer is presented through the
+//! standard `Entropy32` iterator API.
+//!
+//! This source is intentionally conservative about how much entropy
+//! it claims: callers needing full-entropy output should still run it
+//! through [Conditioner](crate::entropy_conditioner::Conditioner).
+
+use core::cell::Cell;
+use kernel::hil::entropy::{Client32, Entropy32, Mode};
+use kernel::hil::time::Time;
+use kernel::ReturnCode;
+
+/// Number of independent interrupt-timing events mixed together
+/// before the accumulated buffer is considered ready.
+const EVENTS_PER_WORD: usize = 256;
+
+/// Maximum number of conditioned words buffered ahead of delivery.
+/// Bounds how large a `get_with` quota this source can honor: a
+/// `min_words` request above this is capped to it.
+const MAX_WORDS: usize = 8;
+
+/// Harvests entropy from the timing jitter between serviced
+/// hardware interrupts.
+pub struct JitterEntropy<'a, T: Time> {
+    timer: &'a T,
+    client: Cell<Option<&'a Client32>>,
+    /// Cycle count at the last serviced interrupt, if any.
+    last: Cell<Option<u32>>,
+    /// Rolling mixing buffer for the word currently being built,
+    /// updated one delta at a time.
+    pool: Cell<u32>,
+    /// Events folded into `pool` since it was last drained.
+    events: Cell<usize>,
+    /// Completed words waiting for delivery.
+    buffer: Cell<[u32; MAX_WORDS]>,
+    /// How many words of `buffer` are filled.
+    filled: Cell<usize>,
+    /// Whether the client is waiting on a `get()`/`get_with()`.
+    requested: Cell<bool>,
+    /// Words needed before `requested` is satisfied; see `Mode`.
+    wanted: Cell<usize>,
+    /// Quality of service requested alongside `wanted`.
+    mode: Cell<Mode>,
+    /// Von Neumann whitening state: the previous bit seen, when an
+    /// unpaired bit is still waiting for its partner.
+    whiten_pending: Cell<Option<bool>>,
+}
+
+impl<'a, T: Time> JitterEntropy<'a, T> {
+    pub fn new(timer: &'a T) -> JitterEntropy<'a, T> {
+        JitterEntropy {
+            timer: timer,
+            client: Cell::new(None),
+            last: Cell::new(None),
+            pool: Cell::new(0),
+            events: Cell::new(0),
+            buffer: Cell::new([0; MAX_WORDS]),
+            filled: Cell::new(0),
+            requested: Cell::new(false),
+            wanted: Cell::new(1),
+            mode: Cell::new(Mode::Partial),
+            whiten_pending: Cell::new(None),
+        }
+    }
+
+    /// Called by the board from its interrupt handler on every
+    /// serviced hardware interrupt.
+    pub fn service_interrupt(&self) {
+        let now = self.timer.now();
+
+        let delta = match self.last.get() {
+            Some(last) => now.wrapping_sub(last),
+            None => {
+                self.last.set(Some(now));
+                return;
+            }
+        };
+        self.last.set(Some(now));
+
+        if let Some(bit) = self.whiten(delta) {
+            let pool = self.pool.get().rotate_left(1) ^ (bit as u32);
+            self.pool.set(pool);
+            self.events.set(self.events.get() + 1);
+        }
+
+        if self.events.get() >= EVENTS_PER_WORD {
+            self.events.set(0);
+            let word = self.pool.get();
+            self.pool.set(0);
+
+            let filled = self.filled.get();
+            if filled < MAX_WORDS {
+                let mut buffer = self.buffer.get();
+                buffer[filled] = word;
+                self.buffer.set(buffer);
+                self.filled.set(filled + 1);
+            }
+            // Otherwise the buffer is already full of undelivered
+            // words because nobody has drained it; drop this one
+            // rather than overwriting entropy still waiting to be
+            // read out.
+        }
+
+        if self.requested.get() && self.quota_met() {
+            self.requested.set(false);
+            self.deliver();
+        }
+    }
+
+    /// Whether `filled` currently satisfies the outstanding request,
+    /// per the quality of service in `mode`.
+    fn quota_met(&self) -> bool {
+        match self.mode.get() {
+            Mode::GoodOnly => self.filled.get() >= self.wanted.get(),
+            Mode::Partial => self.filled.get() >= 1,
+        }
+    }
+
+    /// Von Neumann whitening of the least-significant bit across
+    /// pairs of deltas: consumes bit 0 of two successive deltas,
+    /// discarding 00 and 11 pairs (no information) and emitting the
+    /// first bit of 01/10 pairs. This removes first-order bias from
+    /// the underlying delta without needing to model it. Comparing
+    /// anything other than the same bit position on both halves of
+    /// the pair would mix independent bits together and break the
+    /// whitening guarantee.
+    fn whiten(&self, delta: u32) -> Option<bool> {
+        let bit = delta & 0x1 != 0;
+
+        match self.whiten_pending.get() {
+            None => {
+                self.whiten_pending.set(Some(bit));
+                None
+            }
+            Some(first) => {
+                self.whiten_pending.set(None);
+                if first == bit {
+                    None
+                } else {
+                    Some(first)
+                }
+            }
+        }
+    }
+
+    fn deliver(&self) {
+        let filled = self.filled.get();
+        self.filled.set(0);
+        let buffer = self.buffer.get();
+
+        if let Some(client) = self.client.get() {
+            let mut iter = buffer[..filled].iter().cloned();
+            client.entropy_available(&mut iter, ReturnCode::SUCCESS);
+        }
+    }
+}
+
+impl<'a, T: Time> Entropy32<'a> for JitterEntropy<'a, T> {
+    fn get(&self) -> ReturnCode {
+        self.get_with(1, Mode::Partial)
+    }
+
+    fn get_with(&self, min_words: usize, mode: Mode) -> ReturnCode {
+        // This source can only ever buffer MAX_WORDS ahead of
+        // delivery, so a larger request is honored up to that cap
+        // rather than stalling forever waiting past it.
+        self.wanted.set(min_words.max(1).min(MAX_WORDS));
+        self.mode.set(mode);
+
+        if self.quota_met() {
+            self.deliver();
+        } else {
+            // Not enough independent events have accumulated yet:
+            // remember the request and deliver from
+            // `service_interrupt` once enough have contributed. Per
+            // the `Entropy32::get` contract, SUCCESS means a future
+            // `entropy_available` callback is guaranteed, so we must
+            // not return EOFF here -- the callback just hasn't fired
+            // yet.
+            self.requested.set(true);
+        }
+        ReturnCode::SUCCESS
+    }
+
+    fn cancel(&self) -> ReturnCode {
+        self.requested.set(false);
+        ReturnCode::SUCCESS
+    }
+
+    fn set_client(&'a self, client: &'a Client32) {
+        self.client.set(Some(client));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::Frequency;
+    use test_util::Recorder;
+
+    struct MockFrequency;
+    impl Frequency for MockFrequency {
+        fn frequency() -> u32 {
+            1
+        }
+    }
+
+    struct MockTime {
+        now: Cell<u32>,
+    }
+
+    impl Time for MockTime {
+        type Frequency = MockFrequency;
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+    }
+
+    #[test]
+    fn whiten_discards_matching_pairs_and_emits_differing_ones() {
+        let timer = MockTime { now: Cell::new(0) };
+        let jitter = JitterEntropy::new(&timer);
+
+        // Deltas are chosen with bit 0 != bit 1 so that comparing the
+        // wrong bit position of the second delta (a past bug) would
+        // flip the expected result instead of passing by coincidence.
+        assert_eq!(jitter.whiten(0b01), None); // bit0 = 1, first half of a pair
+        assert_eq!(jitter.whiten(0b01), None); // bit0 = 1, matches first -> discarded
+
+        assert_eq!(jitter.whiten(0b01), None); // bit0 = 1, first half of a new pair
+        assert_eq!(jitter.whiten(0b10), Some(true)); // bit0 = 0, differs -> emits the first bit
+    }
+
+    #[test]
+    fn get_returns_success_even_when_entropy_is_not_yet_ready() {
+        let timer = MockTime { now: Cell::new(0) };
+        let jitter = JitterEntropy::new(&timer);
+        let recorder = Recorder::new();
+        jitter.client.set(Some(&recorder));
+
+        // No interrupts have been serviced yet, so no events have
+        // accumulated. Per the Entropy32::get contract this must
+        // still return SUCCESS, not EOFF: a callback is guaranteed
+        // once enough interrupts arrive, it just hasn't fired yet.
+        assert_eq!(jitter.get(), ReturnCode::SUCCESS);
+        assert!(recorder.calls.borrow().is_empty());
+
+        // Feed enough interrupts to satisfy EVENTS_PER_WORD. Deltas
+        // alternate between 1 and 2 cycles so that bit 0 alternates
+        // between successive interrupts: every whitened pair then
+        // differs and emits an event (a constant delta would make
+        // every pair match and discard, so `events` would never
+        // advance). The very first interrupt only primes `last`, so
+        // double the pair count with margin.
+        let mut now = 0u32;
+        for i in 0..(EVENTS_PER_WORD * 2 + 4) {
+            now = now.wrapping_add(if i % 2 == 0 { 1 } else { 2 });
+            timer.now.set(now);
+            jitter.service_interrupt();
+        }
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0.len(), 1);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+    }
+
+    #[test]
+    fn get_with_good_only_withholds_the_callback_until_the_full_quota_is_ready() {
+        let timer = MockTime { now: Cell::new(0) };
+        let jitter = JitterEntropy::new(&timer);
+        let recorder = Recorder::new();
+        jitter.client.set(Some(&recorder));
+
+        assert_eq!(jitter.get_with(3, Mode::GoodOnly), ReturnCode::SUCCESS);
+        assert!(recorder.calls.borrow().is_empty());
+
+        // Feed enough interrupts to complete 3 whitened words (see
+        // the comment on the single-word test above for why deltas
+        // alternate), plus margin. Under GoodOnly the callback must
+        // not fire until all 3 are ready, and must deliver exactly 3
+        // once they are -- not fewer (withheld too early) or more
+        // (the quota check stops accumulating past it).
+        let mut now = 0u32;
+        for i in 0..(EVENTS_PER_WORD * 3 * 2 + 12) {
+            now = now.wrapping_add(if i % 2 == 0 { 1 } else { 2 });
+            timer.now.set(now);
+            jitter.service_interrupt();
+        }
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0.len(), 3);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+    }
+}
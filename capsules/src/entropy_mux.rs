@@ -0,0 +1,433 @@
+//! Combines several independent `Entropy32` sources into one.
+//!
+//! SP 800-90B requires that a kernel not rely on a single noise
+//! source it cannot independently validate. `EntropyMux` issues
+//! `get()` to every configured backend, and only yields a combined
+//! word to its client once it has a raw word from every
+//! currently-healthy backend, hashed together so the output carries
+//! at least as much entropy as the best input even if one source is
+//! stuck or compromised.
+//!
+//! The number of backends is fixed at construction via a static
+//! slice, matching the way other Tock virtualizers are wired up at
+//! board initialization rather than registered dynamically.
+
+use core::cell::Cell;
+use kernel::hil::digest::Digest;
+use kernel::hil::entropy::{Client32, Continue, Entropy32, Mode};
+use kernel::ReturnCode;
+
+/// Maximum number of backend sources a single `EntropyMux` can
+/// combine. Kept small and fixed so per-source state can live in a
+/// stack-sized array rather than requiring `alloc`.
+pub const MAX_SOURCES: usize = 4;
+
+/// Maximum number of combined words buffered ahead of delivery.
+/// Bounds how large a `get_with` quota the mux can honor: a
+/// `min_words` request above this is capped to it.
+const MAX_WORDS: usize = 8;
+
+/// Per-backend bookkeeping the mux needs while waiting for every
+/// healthy source to contribute a word to the current round.
+pub struct Source<'a> {
+    entropy: &'a Entropy32<'a>,
+    /// This round's word from this source, once it has arrived.
+    word: Cell<Option<u32>>,
+    /// Set once a source fails or is cancelled out-of-band; failed
+    /// sources are excluded from future rounds so one stuck source
+    /// does not wedge the combiner forever.
+    healthy: Cell<bool>,
+}
+
+impl<'a> Source<'a> {
+    pub fn new(entropy: &'a Entropy32<'a>) -> Source<'a> {
+        Source {
+            entropy: entropy,
+            word: Cell::new(None),
+            healthy: Cell::new(true),
+        }
+    }
+}
+
+/// A fixed per-backend proxy `Client32`, so `EntropyMux` learns which
+/// backend a callback came from by construction rather than by
+/// guessing from the order callbacks arrive in. Each `Port` is
+/// registered as the `Client32` of exactly one backend.
+struct Port<'a, H: Digest<[u8; 32]>> {
+    index: usize,
+    mux: Cell<Option<&'a EntropyMux<'a, H>>>,
+}
+
+impl<'a, H: Digest<[u8; 32]>> Port<'a, H> {
+    fn new(index: usize) -> Port<'a, H> {
+        Port {
+            index: index,
+            mux: Cell::new(None),
+        }
+    }
+}
+
+impl<'a, H: Digest<[u8; 32]>> Client32 for Port<'a, H> {
+    fn entropy_available(&self, entropy: &mut Iterator<Item = u32>, error: ReturnCode) -> Continue {
+        match self.mux.get() {
+            Some(mux) => mux.source_done(self.index, entropy, error),
+            None => Continue::Done,
+        }
+    }
+}
+
+pub struct EntropyMux<'a, H: Digest<[u8; 32]>> {
+    sources: &'a [Source<'a>],
+    ports: [Port<'a, H>; MAX_SOURCES],
+    hash: &'a H,
+    client: Cell<Option<&'a Client32>>,
+    /// Combined words from completed rounds, buffered ahead of
+    /// delivery.
+    output: Cell<[u32; MAX_WORDS]>,
+    /// How many words of `output` are filled.
+    ready: Cell<usize>,
+    /// Words needed before the outstanding request is satisfied; see
+    /// `Mode`.
+    wanted: Cell<usize>,
+    /// Quality of service requested alongside `wanted`.
+    mode: Cell<Mode>,
+}
+
+impl<'a, H: Digest<[u8; 32]>> EntropyMux<'a, H> {
+    /// `sources` must not be empty, must not exceed `MAX_SOURCES`
+    /// entries, and must outlive the mux: boards build the backing
+    /// storage statically, as with other Tock virtualizers.
+    pub fn new(sources: &'a [Source<'a>], hash: &'a H) -> EntropyMux<'a, H> {
+        debug_assert!(sources.len() <= MAX_SOURCES);
+        EntropyMux {
+            sources: sources,
+            ports: [Port::new(0), Port::new(1), Port::new(2), Port::new(3)],
+            hash: hash,
+            client: Cell::new(None),
+            output: Cell::new([0; MAX_WORDS]),
+            ready: Cell::new(0),
+            wanted: Cell::new(1),
+            mode: Cell::new(Mode::Partial),
+        }
+    }
+
+    /// Whether `ready` currently satisfies the outstanding request,
+    /// per the quality of service in `mode`.
+    fn quota_met(&self) -> bool {
+        match self.mode.get() {
+            Mode::GoodOnly => self.ready.get() >= self.wanted.get(),
+            Mode::Partial => self.ready.get() >= 1,
+        }
+    }
+
+    /// Issues a fresh `get()` to every healthy backend to start
+    /// another round, without resetting any words already buffered in
+    /// `output` toward the current quota.
+    fn start_round(&self) {
+        for source in self.sources.iter().filter(|s| s.healthy.get()) {
+            source.word.set(None);
+            source.entropy.get();
+        }
+    }
+
+    /// Whether any backend is still considered trustworthy. Once this
+    /// is `false` there is no healthy input left to combine, so a
+    /// round must never be treated as complete: `all()` over an empty
+    /// (all-unhealthy) filtered iterator is vacuously `true`, and
+    /// without this check that would let `combine_and_progress` run a
+    /// hash over zero inputs and hand the client a fixed, predictable
+    /// digest tagged as verified entropy.
+    fn any_healthy(&self) -> bool {
+        self.sources.iter().any(|s| s.healthy.get())
+    }
+
+    fn round_complete(&self) -> bool {
+        self.any_healthy()
+            && self
+                .sources
+                .iter()
+                .filter(|s| s.healthy.get())
+                .all(|s| s.word.get().is_some())
+    }
+
+    /// Folds the current round's per-source words into one combined
+    /// word, buffers it, and either delivers the buffer (if it now
+    /// satisfies the outstanding quota) or starts another round to
+    /// keep working toward it.
+    fn combine_and_progress(&self) {
+        for source in self.sources.iter() {
+            if let Some(word) = source.word.take() {
+                self.hash.add_data(&word.to_le_bytes());
+            }
+        }
+
+        let mut digest = [0u8; 32];
+        self.hash.run(&mut digest);
+        let combined = u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]);
+
+        let ready = self.ready.get();
+        if ready < MAX_WORDS {
+            let mut output = self.output.get();
+            output[ready] = combined;
+            self.output.set(output);
+            self.ready.set(ready + 1);
+        }
+        // Otherwise the buffer is already full of undelivered words
+        // because nobody has drained it; drop this one rather than
+        // overwriting entropy still waiting to be read out.
+
+        if self.quota_met() {
+            self.deliver();
+        } else {
+            self.start_round();
+        }
+    }
+
+    fn deliver(&self) {
+        let ready = self.ready.get();
+        self.ready.set(0);
+        let output = self.output.get();
+
+        if let Some(client) = self.client.get() {
+            let mut iter = output[..ready].iter().cloned();
+            client.entropy_available(&mut iter, ReturnCode::SUCCESS);
+        }
+    }
+
+    /// Called by the `Port` at `index` with that specific backend's
+    /// callback, so a word or a failure is always attributed to the
+    /// source that actually produced it.
+    fn source_done(&self, index: usize, entropy: &mut Iterator<Item = u32>, error: ReturnCode) -> Continue {
+        let source = &self.sources[index];
+
+        if error != ReturnCode::SUCCESS {
+            // Exclude this source from future rounds regardless of
+            // whether it also handed back a sample, so a source that
+            // starts failing can never permanently stall the round.
+            source.healthy.set(false);
+        } else if let Some(word) = entropy.next() {
+            source.word.set(Some(word));
+        }
+
+        if !self.any_healthy() {
+            // No source left to combine: report failure rather than
+            // let an empty round look "complete".
+            if let Some(client) = self.client.get() {
+                client.entropy_available(&mut core::iter::empty(), ReturnCode::FAIL);
+            }
+            return Continue::Done;
+        }
+
+        if self.round_complete() {
+            self.combine_and_progress();
+        }
+
+        // This specific backend has now contributed (or permanently
+        // failed) for the current round, regardless of whether a
+        // different, slower backend still owes the round a word. Per
+        // the `Client32` contract, telling it `More` would mean
+        // asking it to keep generating and calling back, which would
+        // needlessly keep an already-satisfied source running (or
+        // leave it with a dangling callback obligation) until the
+        // round completes elsewhere; it is only asked for a fresh
+        // word again by the next `get()`.
+        Continue::Done
+    }
+}
+
+impl<'a, H: Digest<[u8; 32]>> Entropy32<'a> for EntropyMux<'a, H> {
+    fn get(&self) -> ReturnCode {
+        self.get_with(1, Mode::Partial)
+    }
+
+    fn get_with(&self, min_words: usize, mode: Mode) -> ReturnCode {
+        if !self.any_healthy() {
+            // No callback can ever arrive: every backend has failed.
+            return ReturnCode::FAIL;
+        }
+
+        // This mux can only ever buffer MAX_WORDS ahead of delivery,
+        // so a larger request is honored up to that cap rather than
+        // stalling forever waiting past it.
+        self.wanted.set(min_words.max(1).min(MAX_WORDS));
+        self.mode.set(mode);
+
+        if self.quota_met() {
+            self.deliver();
+        } else {
+            self.start_round();
+        }
+        ReturnCode::SUCCESS
+    }
+
+    fn cancel(&self) -> ReturnCode {
+        for source in self.sources.iter() {
+            source.entropy.cancel();
+            source.word.set(None);
+        }
+        self.ready.set(0);
+        ReturnCode::SUCCESS
+    }
+
+    fn set_client(&'a self, client: &'a Client32) {
+        self.client.set(Some(client));
+        for (i, source) in self.sources.iter().enumerate() {
+            self.ports[i].mux.set(Some(self));
+            source.entropy.set_client(&self.ports[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::vec::Vec;
+    use test_util::Recorder;
+
+    struct StubSource;
+
+    impl<'a> Entropy32<'a> for StubSource {
+        fn get(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn cancel(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn set_client(&'a self, _client: &'a Client32) {}
+    }
+
+    struct MockDigest {
+        absorbed: RefCell<Vec<u8>>,
+    }
+
+    impl MockDigest {
+        fn new() -> MockDigest {
+            MockDigest {
+                absorbed: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Digest<[u8; 32]> for MockDigest {
+        fn add_data(&self, data: &[u8]) {
+            self.absorbed.borrow_mut().extend_from_slice(data);
+        }
+        fn run(&self, digest: &mut [u8; 32]) {
+            // A digest that depends on what was absorbed, so tests can
+            // tell a real combined round apart from an empty one.
+            *digest = [0u8; 32];
+            digest[0] = self.absorbed.borrow().len() as u8;
+            self.absorbed.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn a_single_failing_source_reports_failure_not_a_fabricated_digest() {
+        let stub = StubSource;
+        let sources = [Source::new(&stub)];
+        let hash = MockDigest::new();
+        let mux = EntropyMux::new(&sources, &hash);
+
+        let recorder = Recorder::new();
+        mux.client.set(Some(&recorder));
+
+        let result = mux.source_done(0, &mut core::iter::empty(), ReturnCode::FAIL);
+
+        assert_eq!(result, Continue::Done);
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].0.is_empty());
+        assert!(calls[0].1 == ReturnCode::FAIL);
+        assert_eq!(mux.get(), ReturnCode::FAIL);
+    }
+
+    #[test]
+    fn failure_is_reported_only_once_every_source_has_failed() {
+        let stub_a = StubSource;
+        let stub_b = StubSource;
+        let sources = [Source::new(&stub_a), Source::new(&stub_b)];
+        let hash = MockDigest::new();
+        let mux = EntropyMux::new(&sources, &hash);
+
+        let recorder = Recorder::new();
+        mux.client.set(Some(&recorder));
+
+        // Source 0 fails; it is done contributing to this round
+        // either way (it has nothing left to give), so it is told
+        // `Done` even though the round overall is not complete yet --
+        // source 1 is still healthy and hasn't reported, so no
+        // callback should fire.
+        let result = mux.source_done(0, &mut core::iter::empty(), ReturnCode::FAIL);
+        assert_eq!(result, Continue::Done);
+        assert!(recorder.calls.borrow().is_empty());
+
+        // Source 1 also fails: no healthy source remains, so the mux
+        // must report failure instead of combining zero inputs.
+        let result = mux.source_done(1, &mut core::iter::empty(), ReturnCode::FAIL);
+        assert_eq!(result, Continue::Done);
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].1 == ReturnCode::FAIL);
+    }
+
+    #[test]
+    fn a_round_completes_once_every_healthy_source_reports() {
+        let stub_a = StubSource;
+        let stub_b = StubSource;
+        let sources = [Source::new(&stub_a), Source::new(&stub_b)];
+        let hash = MockDigest::new();
+        let mux = EntropyMux::new(&sources, &hash);
+
+        let recorder = Recorder::new();
+        mux.client.set(Some(&recorder));
+
+        // Source 0 has now contributed its word for the round and is
+        // told `Done`, even though source 1 hasn't reported yet and
+        // the round as a whole isn't complete.
+        let result = mux.source_done(0, &mut core::iter::once(1u32), ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+        assert!(recorder.calls.borrow().is_empty());
+
+        let result = mux.source_done(1, &mut core::iter::once(2u32), ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0.len(), 1);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+    }
+
+    #[test]
+    fn get_with_good_only_withholds_the_callback_until_every_round_is_combined() {
+        let stub = StubSource;
+        let sources = [Source::new(&stub)];
+        let hash = MockDigest::new();
+        let mux = EntropyMux::new(&sources, &hash);
+
+        let recorder = Recorder::new();
+        mux.client.set(Some(&recorder));
+
+        assert_eq!(mux.get_with(2, Mode::GoodOnly), ReturnCode::SUCCESS);
+        assert!(recorder.calls.borrow().is_empty());
+
+        // First round completes: only 1 of the 2 wanted words is
+        // buffered so far, so GoodOnly must not deliver yet -- it
+        // must instead start another round rather than stalling.
+        let result = mux.source_done(0, &mut core::iter::once(1u32), ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+        assert!(recorder.calls.borrow().is_empty());
+
+        // Second round completes the quota: deliver exactly the 2
+        // buffered words, not 1 (delivered too early) or more (the
+        // quota check should have stopped accumulating past it).
+        let result = mux.source_done(0, &mut core::iter::once(2u32), ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+
+        let calls = recorder.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0.len(), 2);
+        assert!(calls[0].1 == ReturnCode::SUCCESS);
+    }
+}
@@ -0,0 +1,20 @@
+//! Drivers and capsules for Tock.
+//!
+//! This crate lives on top of `kernel` and implements the HILs kernel
+//! defines, plus board-facing virtualization and conditioning logic
+//! that doesn't belong in the kernel itself.
+
+#![forbid(unsafe_code)]
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(test)]
+extern crate core;
+extern crate kernel;
+
+pub mod entropy_conditioner;
+pub mod entropy_health_test;
+pub mod entropy_jitter;
+pub mod entropy_mux;
+pub mod reseeding_rng;
+#[cfg(test)]
+mod test_util;
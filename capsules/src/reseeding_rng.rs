@@ -0,0 +1,369 @@
+//! Reseeding DRBG: a fast synchronous `Rng` backed by a slow
+//! `Entropy32` source.
+//!
+//! Hardware entropy sources reachable through
+//! [Entropy32](kernel::hil::entropy::Entropy32) are low-bandwidth and
+//! asynchronous, but most consumers want cheap, synchronous random
+//! bytes. `ReseedingRng` draws a seed from an `Entropy32` source, uses
+//! it to key a deterministic generator, and serves
+//! [Rng](kernel::hil::rng::Rng) requests directly from that generator
+//! until a configurable reseed interval elapses, at which point it
+//! transparently reseeds and rekeys.
+
+use core::cell::Cell;
+use kernel::hil::entropy::{Client32, Continue, Entropy32, Mode};
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::AES128Ctr;
+use kernel::hil::time::{self, Alarm, Client, Frequency, Time};
+use kernel::ReturnCode;
+
+/// Number of 32-bit words of entropy drawn from the source to key and
+/// IV the block cipher on each reseed: 4 words (128 bits) of key plus
+/// 4 words (128 bits) of initial counter value.
+const SEED_WORDS: usize = 8;
+
+/// Bytes of keystream served before `ReseedingRng` automatically
+/// reseeds, when no `reseed_bytes` budget is configured.
+const DEFAULT_RESEED_BYTES: usize = 1024 * 1024;
+
+pub struct ReseedingRng<'a, E: Entropy32<'a>, A: Alarm> {
+    source: &'a E,
+    cipher: &'a AES128Ctr,
+    alarm: &'a A,
+    reseed_bytes: usize,
+    reseed_interval: Option<u32>,
+    /// Words of the in-progress seed collected so far.
+    seed: Cell<[u32; SEED_WORDS]>,
+    seed_collected: Cell<usize>,
+    /// Bytes served from the current key since the last reseed.
+    served: Cell<usize>,
+    seeded: Cell<bool>,
+    reseed_pending: Cell<bool>,
+}
+
+impl<'a, E: Entropy32<'a>, A: Alarm> ReseedingRng<'a, E, A> {
+    pub fn new(
+        source: &'a E,
+        cipher: &'a AES128Ctr,
+        alarm: &'a A,
+        reseed_bytes: Option<usize>,
+        reseed_interval: Option<u32>,
+    ) -> ReseedingRng<'a, E, A> {
+        ReseedingRng {
+            source: source,
+            cipher: cipher,
+            alarm: alarm,
+            reseed_bytes: reseed_bytes.unwrap_or(DEFAULT_RESEED_BYTES),
+            reseed_interval: reseed_interval,
+            seed: Cell::new([0; SEED_WORDS]),
+            seed_collected: Cell::new(0),
+            served: Cell::new(0),
+            seeded: Cell::new(false),
+            reseed_pending: Cell::new(false),
+        }
+    }
+
+    /// Kicks off the initial seeding; boards should call this during
+    /// initialization so early callers can observe `seeded()` go
+    /// `true` as soon as possible rather than triggering the first
+    /// `get()` themselves.
+    pub fn initialize(&self) {
+        self.reseed();
+    }
+
+    fn reseed(&self) {
+        if self.reseed_pending.get() {
+            return;
+        }
+        self.reseed_pending.set(true);
+        self.seed_collected.set(0);
+        self.source.get_with(SEED_WORDS, Mode::GoodOnly);
+    }
+
+    fn rekey(&self) {
+        let seed = self.seed.get();
+        let mut key = [0u8; 16];
+        let mut counter = [0u8; 16];
+        for i in 0..4 {
+            key[i * 4..i * 4 + 4].copy_from_slice(&seed[i].to_le_bytes());
+            counter[i * 4..i * 4 + 4].copy_from_slice(&seed[4 + i].to_le_bytes());
+        }
+        self.cipher.set_key(&key);
+        self.cipher.set_counter(&counter);
+
+        self.served.set(0);
+        self.seeded.set(true);
+        self.reseed_pending.set(false);
+
+        if let Some(interval) = self.reseed_interval {
+            let when = self
+                .alarm
+                .now()
+                .wrapping_add(interval.wrapping_mul(<A::Frequency>::frequency()));
+            self.alarm.set_alarm(when);
+        }
+    }
+}
+
+impl<'a, E: Entropy32<'a>, A: Alarm> Rng for ReseedingRng<'a, E, A> {
+    fn randomize(&self, buffer: &mut [u8]) -> usize {
+        if !self.seeded.get() {
+            return 0;
+        }
+
+        let written = self.cipher.crypt(buffer);
+
+        let served = self.served.get() + written;
+        self.served.set(served);
+        if served >= self.reseed_bytes {
+            self.reseed();
+        }
+
+        written
+    }
+
+    fn seeded(&self) -> bool {
+        self.seeded.get()
+    }
+}
+
+impl<'a, E: Entropy32<'a>, A: Alarm> Client32 for ReseedingRng<'a, E, A> {
+    fn entropy_available(
+        &self,
+        entropy: &mut Iterator<Item = u32>,
+        error: ReturnCode,
+    ) -> Continue {
+        if error != ReturnCode::SUCCESS {
+            // Try again. Requests are not buffered meanwhile:
+            // `randomize` keeps serving from the current key, or
+            // returns 0 if the generator has never been seeded.
+            self.reseed_pending.set(false);
+            self.reseed();
+            return Continue::Done;
+        }
+
+        let mut seed = self.seed.get();
+        let mut collected = self.seed_collected.get();
+        while collected < SEED_WORDS {
+            match entropy.next() {
+                Some(word) => {
+                    seed[collected] = word;
+                    collected += 1;
+                }
+                None => {
+                    self.seed.set(seed);
+                    self.seed_collected.set(collected);
+                    return Continue::More;
+                }
+            }
+        }
+        self.seed.set(seed);
+        self.seed_collected.set(collected);
+        self.rekey();
+        Continue::Done
+    }
+}
+
+impl<'a, E: Entropy32<'a>, A: Alarm> time::Client for ReseedingRng<'a, E, A> {
+    fn fired(&self) {
+        self.reseed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockSource {
+        get_with_calls: Cell<usize>,
+    }
+
+    impl MockSource {
+        fn new() -> MockSource {
+            MockSource {
+                get_with_calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl<'a> Entropy32<'a> for MockSource {
+        fn get(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn get_with(&self, _min_words: usize, _mode: Mode) -> ReturnCode {
+            self.get_with_calls.set(self.get_with_calls.get() + 1);
+            ReturnCode::SUCCESS
+        }
+        fn cancel(&self) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+        fn set_client(&'a self, _client: &'a Client32) {}
+    }
+
+    struct MockFrequency;
+    impl Frequency for MockFrequency {
+        fn frequency() -> u32 {
+            1
+        }
+    }
+
+    struct MockAlarm {
+        now: Cell<u32>,
+        alarm: Cell<u32>,
+        set_alarm_calls: Cell<usize>,
+    }
+
+    impl MockAlarm {
+        fn new() -> MockAlarm {
+            MockAlarm {
+                now: Cell::new(0),
+                alarm: Cell::new(0),
+                set_alarm_calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl Time for MockAlarm {
+        type Frequency = MockFrequency;
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+    }
+
+    impl Alarm for MockAlarm {
+        fn set_alarm(&self, tics: u32) {
+            self.alarm.set(tics);
+            self.set_alarm_calls.set(self.set_alarm_calls.get() + 1);
+        }
+        fn get_alarm(&self) -> u32 {
+            self.alarm.get()
+        }
+        fn set_client(&self, _client: &'static time::Client) {}
+    }
+
+    struct MockCipher {
+        key: Cell<[u8; 16]>,
+        counter: Cell<[u8; 16]>,
+        crypt_calls: Cell<usize>,
+    }
+
+    impl MockCipher {
+        fn new() -> MockCipher {
+            MockCipher {
+                key: Cell::new([0; 16]),
+                counter: Cell::new([0; 16]),
+                crypt_calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl AES128Ctr for MockCipher {
+        fn set_key(&self, key: &[u8]) {
+            let mut buf = [0u8; 16];
+            buf.copy_from_slice(key);
+            self.key.set(buf);
+        }
+        fn set_counter(&self, counter: &[u8]) {
+            let mut buf = [0u8; 16];
+            buf.copy_from_slice(counter);
+            self.counter.set(buf);
+        }
+        fn crypt(&self, buf: &mut [u8]) -> usize {
+            self.crypt_calls.set(self.crypt_calls.get() + 1);
+            for byte in buf.iter_mut() {
+                *byte = 0x42;
+            }
+            buf.len()
+        }
+    }
+
+    #[test]
+    fn seed_accumulates_across_callbacks_and_rekeys() {
+        let source = MockSource::new();
+        let alarm = MockAlarm::new();
+        let cipher = MockCipher::new();
+        let rng = ReseedingRng::new(&source, &cipher, &alarm, Some(1024), Some(60));
+
+        rng.initialize();
+        assert_eq!(source.get_with_calls.get(), 1);
+        assert!(!rng.seeded());
+
+        let mut first_half = (0u32..4).into_iter();
+        let result = rng.entropy_available(&mut first_half, ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::More);
+        assert!(!rng.seeded());
+
+        let mut second_half = (4u32..8).into_iter();
+        let result = rng.entropy_available(&mut second_half, ReturnCode::SUCCESS);
+        assert_eq!(result, Continue::Done);
+        assert!(rng.seeded());
+
+        // Key = words 0..4 little-endian, counter = words 4..8.
+        assert_eq!(&cipher.key.get()[0..4], &0u32.to_le_bytes());
+        assert_eq!(&cipher.counter.get()[0..4], &4u32.to_le_bytes());
+        assert_eq!(alarm.set_alarm_calls.get(), 1);
+    }
+
+    #[test]
+    fn randomize_is_refused_before_seeded() {
+        let source = MockSource::new();
+        let alarm = MockAlarm::new();
+        let cipher = MockCipher::new();
+        let rng = ReseedingRng::new(&source, &cipher, &alarm, Some(1024), None);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(rng.randomize(&mut buf), 0);
+        assert_eq!(cipher.crypt_calls.get(), 0);
+    }
+
+    #[test]
+    fn randomize_reseeds_once_the_byte_budget_is_exhausted() {
+        let source = MockSource::new();
+        let alarm = MockAlarm::new();
+        let cipher = MockCipher::new();
+        let rng = ReseedingRng::new(&source, &cipher, &alarm, Some(16), None);
+
+        rng.initialize();
+        let mut seed = (0u32..8).into_iter();
+        rng.entropy_available(&mut seed, ReturnCode::SUCCESS);
+        assert!(rng.seeded());
+        assert_eq!(source.get_with_calls.get(), 1);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(rng.randomize(&mut buf), 16);
+        // 16 bytes served against a 16-byte budget: a reseed is due.
+        assert_eq!(source.get_with_calls.get(), 2);
+    }
+
+    #[test]
+    fn a_failed_callback_retries_the_reseed() {
+        let source = MockSource::new();
+        let alarm = MockAlarm::new();
+        let cipher = MockCipher::new();
+        let rng = ReseedingRng::new(&source, &cipher, &alarm, Some(1024), None);
+
+        rng.initialize();
+        assert_eq!(source.get_with_calls.get(), 1);
+
+        let result = rng.entropy_available(&mut core::iter::empty(), ReturnCode::FAIL);
+        assert_eq!(result, Continue::Done);
+        assert!(!rng.seeded());
+        assert_eq!(source.get_with_calls.get(), 2);
+    }
+
+    #[test]
+    fn fired_triggers_a_reseed() {
+        let source = MockSource::new();
+        let alarm = MockAlarm::new();
+        let cipher = MockCipher::new();
+        let rng = ReseedingRng::new(&source, &cipher, &alarm, Some(1024), Some(60));
+
+        rng.initialize();
+        let mut seed = (0u32..8).into_iter();
+        rng.entropy_available(&mut seed, ReturnCode::SUCCESS);
+        assert_eq!(source.get_with_calls.get(), 1);
+
+        rng.fired();
+        assert_eq!(source.get_with_calls.get(), 2);
+    }
+}
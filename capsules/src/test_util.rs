@@ -0,0 +1,29 @@
+//! Test-only fixtures shared across this crate's capsule unit tests.
+
+#![cfg(test)]
+
+use core::cell::RefCell;
+use kernel::hil::entropy::{Client32, Continue};
+use kernel::ReturnCode;
+use std::vec::Vec;
+
+/// Records every `Client32` callback it receives, so capsule tests can
+/// assert on how many times a client was called and with what.
+pub struct Recorder {
+    pub calls: RefCell<Vec<(Vec<u32>, ReturnCode)>>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            calls: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Client32 for Recorder {
+    fn entropy_available(&self, entropy: &mut Iterator<Item = u32>, error: ReturnCode) -> Continue {
+        self.calls.borrow_mut().push((entropy.collect(), error));
+        Continue::Done
+    }
+}
@@ -0,0 +1,15 @@
+//! Capabilities: marker traits that gate access to sensitive or
+//! easily-misused kernel interfaces. A capsule that wants to call a
+//! function guarded by a capability must be given (typically by the
+//! board's main.rs, at a scope the author of main.rs controls) a
+//! value of the appropriate marker type. Because these traits are
+//! `unsafe`, only code the board trusts should construct one.
+
+/// The capability to enable a source's raw, pre-conditioning sample
+/// tap via `hil::entropy::RawEntropy::set_raw_client`.
+///
+/// Raw samples bypass health testing and conditioning entirely, so
+/// this is restricted to privileged diagnostic capsules used for
+/// compliance validation and field debugging, not general application
+/// code.
+pub unsafe trait RawEntropyCapability {}
@@ -0,0 +1,20 @@
+//! A synchronous cryptographic digest (hash) interface.
+//!
+//! Unlike most Tock HILs, `Digest` is synchronous: implementations
+//! are expected to be software hash functions (e.g. SHA-256) that
+//! complete in bounded time without needing an interrupt or callback,
+//! so capsules that fold a hash into a larger async protocol of their
+//! own (entropy conditioning, source combination) can drive it
+//! directly from their own callbacks.
+
+/// A cryptographic hash function producing a fixed-size digest `T`.
+pub trait Digest<T: Copy> {
+    /// Absorbs more input data into the running hash state.
+    fn add_data(&self, data: &[u8]);
+
+    /// Finalizes the hash over all data absorbed since the last call
+    /// to `run` (or since construction), writes the digest into
+    /// `digest`, and resets the running state so a fresh `add_data`
+    /// sequence can begin immediately.
+    fn run(&self, digest: &mut T);
+}
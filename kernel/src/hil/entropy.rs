@@ -83,6 +83,7 @@
 //! }
 //! ```
 
+use capabilities;
 use returncode::ReturnCode;
 /// Denotes whether the [Client](trait.Client.html) wants to be notified when
 /// `More` randomness is available or if they are `Done`
@@ -94,6 +95,19 @@ pub enum Continue {
     Done,
 }
 
+/// Quality of service requested from a call to `get_with`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Mode {
+    /// Withhold the `entropy_available` callback until at least
+    /// `min_words` (or `min_bytes`, for `Entropy8`) of full entropy
+    /// are buffered and can be delivered in one callback.
+    GoodOnly,
+    /// Fire `entropy_available` as soon as any entropy is available,
+    /// even if less than was requested, and let the client drain
+    /// what exists. This is the behavior `get()` has always had.
+    Partial,
+}
+
 /// Generic interface for a 32-bit entropy source.
 ///
 /// Implementors should assume the client implements the
@@ -112,6 +126,28 @@ pub trait Entropy32<'a> {
     ///     powered.
     fn get(&self) -> ReturnCode;
 
+    /// Initiate the acquisition of at least `min_words` words of
+    /// entropy, with the delivery quality given by `mode`.
+    ///
+    /// Under `Mode::GoodOnly`, `entropy_available` will not be called
+    /// until at least `min_words` words are buffered and ready, so
+    /// virtualized consumers each get their requested quota and
+    /// conditioning/health-test wrappers can ask for exactly one
+    /// output block without repeatedly polling. Under
+    /// `Mode::Partial`, `min_words` is advisory: `entropy_available`
+    /// fires as soon as any entropy is available, identically to
+    /// `get()`.
+    ///
+    /// Takes the same return values as `get()`. The default
+    /// implementation ignores `min_words` and `mode` and simply calls
+    /// `get()`, so implementations that have not been updated to
+    /// honor a minimum keep today's "call me when anything is ready"
+    /// behavior.
+    fn get_with(&self, min_words: usize, mode: Mode) -> ReturnCode {
+        let _ = (min_words, mode);
+        self.get()
+    }
+
     /// Cancel acquisition of entropy.
     ///
     /// There are three valid return values:
@@ -169,6 +205,16 @@ pub trait Entropy8<'a> {
     ///     powered.
     fn get(&self) -> ReturnCode;
 
+    /// Initiate the acquisition of at least `min_bytes` bytes of
+    /// entropy, with the delivery quality given by `mode`. See
+    /// `Entropy32::get_with` for the semantics of `mode`; the default
+    /// implementation likewise ignores `min_bytes` and `mode` and
+    /// calls `get()`.
+    fn get_with(&self, min_bytes: usize, mode: Mode) -> ReturnCode {
+        let _ = (min_bytes, mode);
+        self.get()
+    }
+
     /// Cancel acquisition of entropy.
     ///
     /// There are three valid return values:
@@ -207,3 +253,130 @@ pub trait Client8 {
     /// entropy.
     fn entropy_available(&self, entropy: &mut Iterator<Item = u8>, error: ReturnCode) -> Continue;
 }
+
+/// A diagnostic tap onto a source's raw, unconditioned samples.
+///
+/// Compliance validation and field debugging both need the bits a
+/// source produced before any health testing or conditioning threw
+/// them away, which `Entropy32`/`Entropy8` deliberately hide. A source
+/// may optionally implement `RawEntropy` to expose that stream, gated
+/// behind a capability so only a privileged diagnostic capsule can
+/// turn it on.
+///
+/// **The samples this trait yields are NOT full-entropy and MUST NOT
+/// be fed directly into an RNG.** They exist for offline min-entropy
+/// estimation and on-device health-test tuning only.
+pub trait RawEntropy<'a> {
+    /// Enables the raw sample tap and registers `client` to receive
+    /// samples. The normal conditioned `Entropy32`/`Entropy8` path
+    /// continues to function while the tap is active.
+    ///
+    /// `_cap` proves the caller holds a capability authorizing access
+    /// to raw, pre-conditioning samples; see `kernel::capabilities`.
+    fn set_raw_client(
+        &'a self,
+        client: &'a RawClient,
+        _cap: &capabilities::RawEntropyCapability,
+    );
+
+    /// Disables the raw sample tap. No further `raw_sample_available`
+    /// callbacks will be issued.
+    fn disable_raw_client(&self);
+}
+
+/// A [RawEntropy](trait.RawEntropy.html) client.
+pub trait RawClient {
+    /// Called with one raw, unconditioned sample and the
+    /// monotonically increasing index of that sample in the source's
+    /// output stream, counted from when the tap was enabled.
+    ///
+    /// As with `Client32`/`Client8`, this output is not full-entropy
+    /// and must never be used as RNG input directly.
+    fn raw_sample_available(&self, sample: u32, index: usize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// A board's main.rs is the only code trusted to construct a
+    /// capability; a dummy marker stands in for that here.
+    struct TestCapability;
+    unsafe impl capabilities::RawEntropyCapability for TestCapability {}
+
+    /// A minimal `RawEntropy` source: `set_raw_client`/
+    /// `disable_raw_client` just record whether the tap is enabled,
+    /// enough to prove the capability-gated signature compiles and
+    /// wires up a client the way the trait's doc comment claims.
+    struct DummySource<'a> {
+        client: Cell<Option<&'a RawClient>>,
+    }
+
+    impl<'a> DummySource<'a> {
+        fn new() -> DummySource<'a> {
+            DummySource {
+                client: Cell::new(None),
+            }
+        }
+
+        fn emit(&self, sample: u32, index: usize) {
+            if let Some(client) = self.client.get() {
+                client.raw_sample_available(sample, index);
+            }
+        }
+    }
+
+    impl<'a> RawEntropy<'a> for DummySource<'a> {
+        fn set_raw_client(&'a self, client: &'a RawClient, _cap: &capabilities::RawEntropyCapability) {
+            self.client.set(Some(client));
+        }
+
+        fn disable_raw_client(&self) {
+            self.client.set(None);
+        }
+    }
+
+    struct RecordingClient {
+        samples: Cell<Option<(u32, usize)>>,
+    }
+
+    impl RecordingClient {
+        fn new() -> RecordingClient {
+            RecordingClient {
+                samples: Cell::new(None),
+            }
+        }
+    }
+
+    impl RawClient for RecordingClient {
+        fn raw_sample_available(&self, sample: u32, index: usize) {
+            self.samples.set(Some((sample, index)));
+        }
+    }
+
+    #[test]
+    fn enabling_the_tap_with_a_capability_delivers_raw_samples() {
+        let source = DummySource::new();
+        let client = RecordingClient::new();
+        let cap = TestCapability;
+
+        source.set_raw_client(&client, &cap);
+        source.emit(0x1234, 0);
+
+        assert_eq!(client.samples.get(), Some((0x1234, 0)));
+    }
+
+    #[test]
+    fn disabling_the_tap_stops_further_samples() {
+        let source = DummySource::new();
+        let client = RecordingClient::new();
+        let cap = TestCapability;
+
+        source.set_raw_client(&client, &cap);
+        source.disable_raw_client();
+        source.emit(0x5678, 1);
+
+        assert_eq!(client.samples.get(), None);
+    }
+}
@@ -0,0 +1,8 @@
+//! Hardware interface layers (HILs): traits capsules and chips use to
+//! talk to each other without depending on concrete implementations.
+
+pub mod digest;
+pub mod entropy;
+pub mod rng;
+pub mod symmetric_encryption;
+pub mod time;
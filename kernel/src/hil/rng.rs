@@ -0,0 +1,25 @@
+//! Fast, synchronous random byte generation.
+//!
+//! Unlike [entropy](crate::hil::entropy), whose sources are
+//! low-bandwidth and asynchronous, `Rng` is meant for consumers that
+//! want many random bytes cheaply and can tolerate those bytes being
+//! pseudorandom (drawn from a generator that has been seeded with
+//! real entropy) rather than drawn fresh from a physical source every
+//! time.
+
+/// A synchronous source of pseudorandom bytes, rekeyed periodically
+/// from a true entropy source.
+pub trait Rng {
+    /// Fills `buffer` with random bytes, returning the number of
+    /// bytes written. Always fills the entire buffer once the
+    /// generator has been seeded at least once; see `seeded()` for
+    /// how to avoid calling this before that has happened.
+    fn randomize(&self, buffer: &mut [u8]) -> usize;
+
+    /// Returns `true` once the generator has completed its first
+    /// seeding from the underlying entropy source. Callers that need
+    /// unpredictable output before any first boot race (e.g. stack
+    /// canaries, ASLR offsets) should check this and wait rather than
+    /// consume predictable pre-seed output.
+    fn seeded(&self) -> bool;
+}
@@ -0,0 +1,22 @@
+//! Synchronous symmetric-key block cipher interfaces.
+//!
+//! Like `hil::digest`, this is a synchronous subset: implementations
+//! are software (or single-shot hardware) ciphers that complete
+//! within the call, for capsules that need keystream generation as
+//! part of a larger protocol they already drive asynchronously
+//! themselves.
+
+/// AES-128 in CTR mode, used as a deterministic keystream generator.
+pub trait AES128Ctr {
+    /// Sets the 128-bit key used for subsequent `crypt` calls.
+    fn set_key(&self, key: &[u8]);
+
+    /// Sets the 128-bit initial counter value used for subsequent
+    /// `crypt` calls.
+    fn set_counter(&self, counter: &[u8]);
+
+    /// Fills `buf` with keystream bytes, advancing the counter by the
+    /// number of blocks consumed. Returns the number of bytes
+    /// written, which is always `buf.len()`.
+    fn crypt(&self, buf: &mut [u8]) -> usize;
+}
@@ -0,0 +1,34 @@
+//! Timing: a free-running counter, and one-shot alarms built on it.
+
+/// Identifies a counter's tick rate.
+pub trait Frequency {
+    /// Returns the number of ticks per second.
+    fn frequency() -> u32;
+}
+
+/// A free-running counter.
+pub trait Time {
+    /// The rate at which this counter's ticks advance.
+    type Frequency: Frequency;
+
+    /// Returns the current tick count. Wraps on overflow.
+    fn now(&self) -> u32;
+}
+
+/// A single-shot alarm built on top of a `Time` counter.
+pub trait Alarm: Time {
+    /// Sets the alarm to fire the next time the counter reaches `tics`.
+    fn set_alarm(&self, tics: u32);
+
+    /// Returns the tick value the alarm is currently set to fire at.
+    fn get_alarm(&self) -> u32;
+
+    /// Sets the client to receive the `fired` callback.
+    fn set_client(&self, client: &'static Client);
+}
+
+/// An [Alarm](trait.Alarm.html) client.
+pub trait Client {
+    /// Called once when the alarm's tick count is reached.
+    fn fired(&self);
+}